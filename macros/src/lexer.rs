@@ -0,0 +1,154 @@
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+
+/// A flat domain token produced from the macro's incoming `Vec<TokenTree>`.
+///
+/// The lexer walks the token trees once and lowers them into this small,
+/// unambiguous alphabet, so the LALRPOP grammar never has to look at raw
+/// `proc_macro` types. Every token keeps the `Span` of the source it came
+/// from for diagnostics.
+#[derive(Clone, Debug)]
+pub enum Token {
+    /// `<`
+    LAngle(Span),
+    /// `>`
+    RAngle(Span),
+    /// `/`
+    Slash(Span),
+    /// `=`
+    Eq(Span),
+    /// A sequence of idents and dashes merged into a single name, with the
+    /// dashes replaced by underscores (see [`html_ident`]).
+    Ident(String, Span),
+    /// A literal child or attribute value.
+    Lit(proc_macro::Literal, Span),
+    /// A brace-delimited group holding an embedded Rust expression.
+    Braced(TokenStream, Span),
+}
+
+impl Token {
+    /// The span of the source token this was lowered from.
+    pub fn span(&self) -> Span {
+        match self {
+            Token::LAngle(span)
+            | Token::RAngle(span)
+            | Token::Slash(span)
+            | Token::Eq(span)
+            | Token::Ident(_, span)
+            | Token::Lit(_, span)
+            | Token::Braced(_, span) => *span,
+        }
+    }
+}
+
+/// How confident we are that a suggested fix is correct, mirroring rustc's
+/// `rustc_errors::Applicability`.
+#[derive(Clone, Copy, Debug)]
+pub enum Applicability {
+    /// The suggestion is definitely what the user intended and can be applied
+    /// automatically by an editor.
+    MachineApplicable,
+    /// The suggestion may be incorrect and should be offered, not applied.
+    MaybeIncorrect,
+}
+
+/// A single-span replacement fix, built from a [`SyntaxError`] when it is
+/// rendered as a diagnostic.
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+
+/// A parse failure, carrying everything needed to render a structured
+/// diagnostic: a primary span and, for a mismatched close tag, the opening
+/// tag's name and span, from which the replacement [`Suggestion`] is built.
+#[derive(Clone, Debug)]
+pub enum SyntaxError {
+    /// A plain error at a single span (unexpected token, bad group, ...).
+    Message { message: String, span: Span },
+    /// A close tag whose name does not match the element it closes.
+    MismatchedClose {
+        open_name: String,
+        open_span: Span,
+        close_name: String,
+        close_span: Span,
+    },
+}
+
+/// Walk a `Vec<TokenTree>` once and emit the flat domain token stream that
+/// the grammar consumes. Contiguous `ident (-ident)*` runs are merged into a
+/// single [`Token::Ident`] so the grammar sees whole HTML names.
+pub fn lex(input: &[TokenTree]) -> Result<Vec<Token>, SyntaxError> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match &input[i] {
+            TokenTree::Punct(p) => match p.as_char() {
+                '<' => out.push(Token::LAngle(p.span())),
+                '>' => out.push(Token::RAngle(p.span())),
+                '/' => out.push(Token::Slash(p.span())),
+                '=' => out.push(Token::Eq(p.span())),
+                other => {
+                    return Err(SyntaxError::Message {
+                        message: format!("unexpected punctuation {:?}", other),
+                        span: p.span(),
+                    })
+                }
+            },
+            TokenTree::Ident(_) => {
+                let (ident, next) = html_ident(input, i);
+                out.push(ident);
+                i = next;
+                continue;
+            }
+            TokenTree::Literal(l) => out.push(Token::Lit(l.clone(), l.span())),
+            TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => {
+                out.push(Token::Braced(g.stream(), g.span()))
+            }
+            TokenTree::Group(g) => {
+                return Err(SyntaxError::Message {
+                    message: "expected a brace-delimited expression".to_string(),
+                    span: g.span(),
+                })
+            }
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// Read a sequence of idents and dashes starting at `start`, and merge them
+/// into a single [`Token::Ident`] with the dashes replaced by underscores.
+fn html_ident(input: &[TokenTree], start: usize) -> (Token, usize) {
+    let mut name = String::new();
+    let mut span: Option<Span> = None;
+    let mut i = start;
+    loop {
+        match input.get(i) {
+            Some(TokenTree::Ident(ident)) => {
+                name += &ident.to_string();
+                span = Some(join(span, ident.span()));
+                i += 1;
+            }
+            _ => break,
+        }
+        // Only continue past a dash if it is followed by another ident.
+        match (input.get(i), input.get(i + 1)) {
+            (Some(TokenTree::Punct(p)), Some(TokenTree::Ident(_))) if p.as_char() == '-' => {
+                name += "_";
+                span = Some(join(span, p.span()));
+                i += 1;
+            }
+            _ => break,
+        }
+    }
+    (Token::Ident(name, span.unwrap()), i)
+}
+
+fn join(span: Option<Span>, next: Span) -> Span {
+    match span {
+        None => next,
+        Some(span) => span.join(next).unwrap_or(span),
+    }
+}
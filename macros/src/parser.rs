@@ -1,160 +1,177 @@
-use pom::combinator::*;
-use pom::{Error, Parser};
-use proc_macro::{
-    Delimiter, Diagnostic, Group, Ident, Level, Literal, Punct, TokenStream, TokenTree,
-};
+use lalrpop_util::{lalrpop_mod, ErrorRecovery, ParseError};
+use proc_macro::{Diagnostic, Ident, Level, Literal, Span, TokenStream, TokenTree};
 
-pub fn unit<'a, I: 'a, A: Clone>(value: A) -> Combinator<impl Parser<'a, I, Output = A>> {
-    comb(move |_, start| Ok((value.clone(), start)))
-}
+use crate::lexer::{lex, Applicability, Suggestion, SyntaxError, Token};
 
-pub fn punct<'a>(punct: char) -> Combinator<impl Parser<'a, TokenTree, Output = Punct>> {
-    comb(move |input: &[TokenTree], start| match input.get(start) {
-        Some(TokenTree::Punct(p)) if p.as_char() == punct => Ok((p.clone(), start + 1)),
-        _ => Err(Error::Mismatch {
-            message: format!("expected {:?}", punct),
-            position: start,
-        }),
-    })
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    pub grammar
+);
+
+/// A node in the parsed `html!` tree.
+pub enum Node {
+    Element(Element),
+    Text(Literal),
+    Block(TokenStream),
 }
 
-pub fn ident<'a>() -> Combinator<impl Parser<'a, TokenTree, Output = Ident>> {
-    comb(|input: &[TokenTree], start| match input.get(start) {
-        Some(TokenTree::Ident(i)) => Ok((i.clone(), start + 1)),
-        _ => Err(Error::Mismatch {
-            message: "expected identifier".to_string(),
-            position: start,
-        }),
-    })
+/// An element: a name, its attributes, and its children.
+pub struct Element {
+    pub name: Ident,
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Node>,
 }
 
-pub fn ident_match<'a>(name: String) -> Combinator<impl Parser<'a, TokenTree, Output = ()>> {
-    comb(move |input: &[TokenTree], start| match input.get(start) {
-        Some(TokenTree::Ident(i)) => {
-            if i.to_string() == name {
-                Ok(((), start + 1))
-            } else {
-                Err(Error::Mismatch {
-                    message: format!("expected '</{}>', found '</{}>'", name, i.to_string()),
-                    position: start,
-                })
-            }
-        }
-        _ => Err(Error::Mismatch {
-            message: "expected identifier".to_string(),
-            position: start,
-        }),
-    })
+/// A single `key = value` attribute.
+pub struct Attribute {
+    pub key: Ident,
+    pub value: Value,
 }
 
-pub fn literal<'a>() -> Combinator<impl Parser<'a, TokenTree, Output = Literal>> {
-    comb(|input: &[TokenTree], start| match input.get(start) {
-        Some(TokenTree::Literal(l)) => Ok((l.clone(), start + 1)),
-        _ => Err(Error::Mismatch {
-            message: "expected literal".to_string(),
-            position: start,
-        }),
-    })
+/// The right-hand side of an attribute.
+pub enum Value {
+    Literal(Literal),
+    Block(TokenStream),
+    Ident(Ident),
 }
 
-pub fn group<'a>() -> Combinator<impl Parser<'a, TokenTree, Output = Group>> {
-    comb(|input: &[TokenTree], start| match input.get(start) {
-        Some(TokenTree::Group(g)) => Ok((g.clone(), start + 1)),
-        _ => Err(Error::Mismatch {
-            message: "expected group".to_string(),
-            position: start,
-        }),
+/// Finish an element once its closing tag has been parsed, checking that the
+/// close name matches the open name. Called from the grammar.
+///
+/// On a mismatch the returned error carries both tags' spans so the diagnostic
+/// can point at the offending close name, label the opening tag, and suggest
+/// the correct replacement.
+pub fn close_element(
+    open: Ident,
+    attributes: Vec<Attribute>,
+    children: Vec<Node>,
+    close: Ident,
+) -> Result<Element, SyntaxError> {
+    if open.to_string() != close.to_string() {
+        return Err(SyntaxError::MismatchedClose {
+            open_name: open.to_string(),
+            open_span: open.span(),
+            close_name: close.to_string(),
+            close_span: close.span(),
+        });
+    }
+    Ok(Element {
+        name: open,
+        attributes,
+        children,
     })
 }
 
-fn to_stream<'a, I: IntoIterator<Item = &'a TokenTree>>(tokens: I) -> TokenStream {
-    let mut stream = TokenStream::new();
-    stream.extend(tokens.into_iter().cloned());
-    stream
-}
+/// Parse an `html!` body into a [`Node`], lexing the token trees into the flat
+/// domain stream and driving the generated LR(1) grammar over it.
+///
+/// The grammar synchronizes at attribute and element boundaries, so a single
+/// malformed attribute or child does not abort the whole parse. Every recovered
+/// error is collected alongside any final fatal error and returned together, so
+/// one `html!` invocation reports all of its syntax problems at once.
+pub fn parse(input: TokenStream) -> Result<Node, Vec<Diagnostic>> {
+    let trees: Vec<TokenTree> = input.into_iter().collect();
+    let tokens = lex(&trees).map_err(|error| vec![syntax_error(error)])?;
+    let stream = tokens
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(index, token)| Ok((index, token, index + 1)));
 
-pub fn type_spec<'a>() -> Combinator<impl Parser<'a, TokenTree, Output = TokenStream>> {
-    let valid = ident().map(TokenTree::Ident)
-        | punct(':').map(TokenTree::Punct)
-        | punct('<').map(TokenTree::Punct)
-        | punct('>').map(TokenTree::Punct)
-        | punct('&').map(TokenTree::Punct)
-        | punct('\'').map(TokenTree::Punct);
-    valid.repeat(1..).collect().map(to_stream)
-}
+    let mut recovered: Vec<ErrorRecovery<usize, Token, SyntaxError>> = Vec::new();
+    let result = grammar::NodeParser::new().parse(&mut recovered, stream);
 
-pub fn dotted_ident<'a>() -> Combinator<impl Parser<'a, TokenTree, Output = TokenTree>> {
-    (ident()
-        + ((punct('.') + ident()).discard() | (punct(':').repeat(2) + ident()).discard())
-            .repeat(0..))
-    .collect()
-    .map(|tokens| {
-        if tokens.len() == 1 {
-            tokens[0].clone()
-        } else {
-            Group::new(Delimiter::Brace, to_stream(tokens)).into()
+    let mut diagnostics: Vec<Diagnostic> = recovered
+        .into_iter()
+        .map(|recovery| parse_error(&tokens, recovery.error))
+        .collect();
+    match result {
+        Ok(node) if diagnostics.is_empty() => Ok(node),
+        Ok(_) => Err(diagnostics),
+        Err(error) => {
+            diagnostics.push(parse_error(&tokens, error));
+            Err(diagnostics)
         }
-    })
+    }
 }
 
-/// Read a sequence of idents and dashes, and merge them into a single ident
-/// with the dashes replaced by underscores.
-pub fn html_ident<'a>() -> Combinator<impl Parser<'a, TokenTree, Output = Ident>> {
-    let start = ident();
-    let next = punct('-') * ident();
-    (start * next.repeat(0..)).collect().map(|stream| {
-        let (span, name) = stream
-            .iter()
-            .fold((None, String::new()), |(span, name), token| {
-                (
-                    match span {
-                        None => Some(token.span()),
-                        Some(span) => span.join(token.span()),
-                    },
-                    match token {
-                        TokenTree::Ident(ident) => name + &ident.to_string(),
-                        TokenTree::Punct(_) => name + "_",
-                        _ => unreachable!(),
-                    },
-                )
-            });
-        Ident::new(&name, span.unwrap())
-    })
+/// Turn a grammar error into a proc_macro diagnostic.
+pub fn parse_error(tokens: &[Token], error: ParseError<usize, Token, SyntaxError>) -> Diagnostic {
+    match error {
+        ParseError::InvalidToken { location } => {
+            Diagnostic::spanned(span_at(tokens, location), Level::Error, "invalid token")
+        }
+        ParseError::UnrecognizedEof {
+            expected: options, ..
+        } => Diagnostic::new(
+            Level::Error,
+            format!("unexpected end of macro!; expected {}", expected(&options)),
+        ),
+        ParseError::UnrecognizedToken {
+            token: (_, token, _),
+            expected: options,
+        } => Diagnostic::spanned(
+            token.span(),
+            Level::Error,
+            format!("unexpected token; expected {}", expected(&options)),
+        ),
+        ParseError::ExtraToken {
+            token: (_, token, _),
+        } => Diagnostic::spanned(token.span(), Level::Error, "unexpected trailing token"),
+        ParseError::User { error } => syntax_error(error),
+    }
 }
 
-/// Turn a parser error into a proc_macro diagnostic.
-pub fn parse_error(input: &[TokenTree], error: &pom::Error) -> Diagnostic {
+fn syntax_error(error: SyntaxError) -> Diagnostic {
     match error {
-        pom::Error::Incomplete => Diagnostic::new(Level::Error, "unexpected end of macro!"),
-        pom::Error::Mismatch { message, position } => {
-            Diagnostic::spanned(input[*position].span(), Level::Error, message.as_str())
-        }
-        pom::Error::Conversion { message, position } => {
-            Diagnostic::spanned(input[*position].span(), Level::Error, message.as_str())
-        }
-        pom::Error::Expect {
-            message,
-            position,
-            inner,
+        SyntaxError::Message { message, span } => Diagnostic::spanned(span, Level::Error, message),
+        SyntaxError::MismatchedClose {
+            open_name,
+            open_span,
+            close_name,
+            close_span,
         } => {
-            let mut diag =
-                Diagnostic::spanned(input[*position].span(), Level::Error, message.as_str());
-            let child = parse_error(input, &inner);
-            diag.span_error(child.spans(), child.message())
-        }
-        pom::Error::Custom {
-            message,
-            position,
-            inner,
-        } => {
-            let mut diag =
-                Diagnostic::spanned(input[*position].span(), Level::Error, message.as_str());
-            if let Some(inner) = inner {
-                let child = parse_error(input, &inner);
-                diag.span_error(child.spans(), child.message())
-            } else {
-                diag
-            }
+            let diag = Diagnostic::spanned(
+                close_span,
+                Level::Error,
+                format!("expected '</{}>', found '</{}>'", open_name, close_name),
+            )
+            .span_note(open_span, format!("element `<{}>` opened here", open_name));
+            render_suggestion(
+                diag,
+                &Suggestion {
+                    span: close_span,
+                    replacement: open_name,
+                    applicability: Applicability::MachineApplicable,
+                },
+            )
         }
     }
 }
+
+/// Render a structured [`Suggestion`] onto a diagnostic.
+///
+/// `proc_macro::Diagnostic` has no machine-applicable suggestion API, so the
+/// replacement is attached as a help note at the suggestion's span; the
+/// suggestion's [`Applicability`] is not part of the rendered diagnostic.
+fn render_suggestion(diag: Diagnostic, suggestion: &Suggestion) -> Diagnostic {
+    diag.span_help(
+        suggestion.span,
+        format!("replace with `{}`", suggestion.replacement),
+    )
+}
+
+fn span_at(tokens: &[Token], location: usize) -> Span {
+    tokens
+        .get(location)
+        .map(Token::span)
+        .unwrap_or_else(Span::call_site)
+}
+
+fn expected(options: &[String]) -> String {
+    if options.is_empty() {
+        "nothing".to_string()
+    } else {
+        options.join(", ")
+    }
+}